@@ -0,0 +1,241 @@
+//! A plain (non-TLS) HTTP server sharing the same accept-loop conventions as [`tls`] —
+//! connection limits, graceful shutdown via [`tls::Handle`], and a [`tls::Listener`]-generic
+//! accept loop — so a plain listener can be driven alongside a TLS one on the same runtime via
+//! [`tls::multi::serve_all`]. This is what makes pairing [`tls::redirect::redirect_to_https`] on
+//! a plain `:80` listener with a TLS `:443` listener actually deliverable: every `init_server*`
+//! function under [`tls`] requires a `rustls::ServerConfig`, and the plain side of that pairing
+//! has none.
+
+use futures::prelude::*;
+use log::{error, info};
+use std::net::ToSocketAddrs;
+use std::sync::Arc;
+use tokio::runtime::TaskExecutor;
+
+use super::handler::NewHandler;
+use super::service::GothamService;
+use super::tls;
+use super::tls::limits::ConnectionLimits;
+use super::tls::shutdown::ShutdownSignal;
+use super::tls::{proxy_protocol, Handle, HttpConfig, Listener};
+use super::{new_runtime, tcp_listener};
+
+/// Default cap on concurrent connections, as a multiple of the number of CPUs.
+const DEFAULT_CONNECTIONS_PER_CPU: usize = 256;
+
+/// Advanced, opt-in options for starting a plain HTTP server, analogous to
+/// [`tls::ServerOptions`] minus anything TLS-specific (there's no handshake to bound, and
+/// nothing to negotiate via ALPN).
+#[derive(Clone)]
+pub struct PlainServerOptions {
+    proxy_protocol: bool,
+    max_connections: usize,
+    http: HttpConfig,
+}
+
+impl Default for PlainServerOptions {
+    fn default() -> Self {
+        PlainServerOptions {
+            proxy_protocol: false,
+            max_connections: num_cpus::get() * DEFAULT_CONNECTIONS_PER_CPU,
+            http: HttpConfig::default(),
+        }
+    }
+}
+
+impl PlainServerOptions {
+    /// Creates a `PlainServerOptions` with every option at its default setting.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// When enabled, each accepted connection is expected to begin with a PROXY protocol v1/v2
+    /// header (as sent by HAProxy/AWS NLB) carrying the real client address. The header is
+    /// stripped before the connection is served, and the decoded address is made available to
+    /// handlers as a `tls::proxy_protocol::ClientAddr`. Connections with a missing or malformed
+    /// header are rejected. Mirrors [`tls::ServerOptions::proxy_protocol`], for a plain listener
+    /// sitting behind the same L4 balancer as a TLS one.
+    pub fn proxy_protocol(mut self, enabled: bool) -> Self {
+        self.proxy_protocol = enabled;
+        self
+    }
+
+    /// Caps the number of connections served concurrently. Once the limit is reached, the
+    /// accept loop stops polling for new sockets until an existing connection finishes, instead
+    /// of accepting unboundedly and piling up work it can't yet serve. Defaults to a multiple of
+    /// the number of CPUs.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = max;
+        self
+    }
+
+    /// Sets the HTTP/1 and HTTP/2 protocol tuning `hyper` uses for every accepted connection.
+    pub fn http(mut self, http: HttpConfig) -> Self {
+        self.http = http;
+        self
+    }
+}
+
+/// Starts a plain HTTP Gotham application with the default number of threads.
+pub fn start<NH, A>(addr: A, new_handler: NH)
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs + 'static,
+{
+    start_with_num_threads(addr, new_handler, num_cpus::get())
+}
+
+/// Starts a plain HTTP Gotham application with a designated number of threads.
+pub fn start_with_num_threads<NH, A>(addr: A, new_handler: NH, threads: usize)
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs + 'static,
+{
+    let runtime = new_runtime(threads);
+    start_on_executor(addr, new_handler, runtime.executor());
+    runtime.shutdown_on_idle();
+}
+
+/// Starts a plain HTTP Gotham application on a designated backing `TaskExecutor`.
+pub fn start_on_executor<NH, A>(addr: A, new_handler: NH, executor: TaskExecutor)
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs + 'static,
+{
+    executor.spawn(init_server(addr, new_handler));
+}
+
+/// Starts a plain HTTP Gotham application on a designated backing `TaskExecutor`, returning a
+/// [`Handle`] that can be used to trigger a graceful shutdown of the server — e.g. so it can be
+/// driven alongside a TLS listener via [`tls::multi::serve_all`] and torn down together.
+pub fn start_with_handle<NH, A>(addr: A, new_handler: NH, executor: TaskExecutor) -> Handle
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs + 'static,
+{
+    let (future, handle) = init_server_with_handle(addr, new_handler);
+    executor.spawn(future);
+    handle
+}
+
+/// Returns a `Future` used to spawn a plain HTTP Gotham application.
+pub fn init_server<NH, A>(addr: A, new_handler: NH) -> impl Future<Output = ()>
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs + 'static,
+{
+    let (future, _handle) = init_server_with_handle(addr, new_handler);
+    future
+}
+
+/// Like [`init_server`], but also returns a [`Handle`] which can be used to trigger a graceful
+/// shutdown: stop accepting new connections, drain the connections already in flight, then
+/// resolve the serving future.
+pub fn init_server_with_handle<NH, A>(
+    addr: A,
+    new_handler: NH,
+) -> (impl Future<Output = ()>, Handle)
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs + 'static,
+{
+    init_server_with_options(addr, new_handler, PlainServerOptions::default())
+}
+
+/// Like [`init_server_with_handle`], but with [`PlainServerOptions`] controlling the advanced,
+/// opt-in behaviour of the accept loop.
+pub fn init_server_with_options<NH, A>(
+    addr: A,
+    new_handler: NH,
+    options: PlainServerOptions,
+) -> (impl Future<Output = ()>, Handle)
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs + 'static,
+{
+    let (handle, signal) = Handle::new();
+
+    let future = tcp_listener(addr)
+        .map_err(|_| ())
+        .and_then(|listener| serve(listener, new_handler, signal, options).map_err(|_| ()))
+        .then(|_| future::ready(())); // Ignore the result
+
+    (future, handle)
+}
+
+/// Starts a plain HTTP Gotham application on an already-bound [`tls::Listener`] — a Unix domain
+/// socket, a custom in-process transport, or a TCP listener obtained some other way than via
+/// `start`'s `ToSocketAddrs`. Returns a [`Handle`] for graceful shutdown, same as
+/// [`init_server_with_handle`].
+pub fn init_server_with_listener<NH, L>(
+    listener: L,
+    new_handler: NH,
+    options: PlainServerOptions,
+) -> (impl Future<Output = ()>, Handle)
+where
+    NH: NewHandler + 'static,
+    L: Listener + 'static,
+{
+    let (handle, signal) = Handle::new();
+    let future = serve(listener, new_handler, signal, options)
+        .map_err(|_| ())
+        .then(|_| future::ready(()));
+    (future, handle)
+}
+
+fn serve<NH, L>(
+    listener: L,
+    new_handler: NH,
+    signal: ShutdownSignal,
+    options: PlainServerOptions,
+) -> impl Future<Output = Result<(), ()>>
+where
+    NH: NewHandler + 'static,
+    L: Listener + 'static,
+{
+    if let Ok(Some(addr)) = Listener::local_addr(&listener) {
+        info!(target: "gotham::start", " Gotham listening on http://{}", addr);
+    } else {
+        info!(target: "gotham::start", " Gotham listening");
+    }
+
+    bind_server(listener, new_handler, signal, options)
+}
+
+async fn bind_server<NH, L>(
+    listener: L,
+    new_handler: NH,
+    shutdown: ShutdownSignal,
+    options: PlainServerOptions,
+) -> Result<(), ()>
+where
+    NH: NewHandler + 'static,
+    L: Listener + 'static,
+{
+    let protocol = Arc::new(options.http.build_auto());
+    let new_handler = Arc::new(new_handler);
+    // There's no handshake to bound here, so the same limit is used for both halves of
+    // `ConnectionLimits`; only `acquire_connection` is ever called.
+    let limits = ConnectionLimits::new(options.max_connections, options.max_connections);
+
+    tls::accept::bind_server(
+        listener,
+        new_handler,
+        shutdown,
+        limits,
+        options.proxy_protocol,
+        move |socket, client_addr, new_handler| {
+            let protocol = protocol.clone();
+
+            async move {
+                let socket = futures_tokio_compat::Compat::new(socket);
+                let new_handler = proxy_protocol::with_client_addr(client_addr, new_handler);
+                let service = GothamService::connect(client_addr, new_handler);
+                if let Err(e) = protocol.serve_connection(socket, service).await {
+                    error!(target: "gotham::tls", "Connection error: {:?}", e);
+                }
+            }
+        },
+    )
+    .await
+}
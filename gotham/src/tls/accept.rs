@@ -0,0 +1,124 @@
+//! The accept loop shared by the plain and TLS listeners: waiting for a connection permit,
+//! accepting a socket, resolving the connecting peer's address (via the PROXY protocol when
+//! asked to), and draining in-flight connections once shutdown is triggered. What it means to
+//! finish a connection — serve a raw socket directly, or complete a TLS handshake first — is
+//! left to the per-connection `finish` callback, so this loop doesn't need to know which one
+//! it's driving.
+
+use futures::channel::oneshot;
+use futures::prelude::*;
+use futures::select;
+use log::error;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::handler::NewHandler;
+
+use super::limits::ConnectionLimits;
+use super::listener::{Connection, Listener};
+use super::shutdown::{drain, ConnectionGuard, ShutdownSignal};
+use super::unknown_peer_addr;
+
+/// Drives `listener`'s accept loop until `shutdown` is triggered, then drains in-flight
+/// connections before resolving.
+///
+/// For each connection: waits for a permit from `limits` before accepting it, resolves its client address
+/// (via the PROXY protocol when `proxy_protocol` is set, otherwise the transport's own peer
+/// address), then spawns `finish` to complete and serve it. The permit is held for the lifetime
+/// of `finish`'s returned future. Connections still in flight when draining ends (because the
+/// shutdown timeout elapsed) are dropped.
+pub(crate) async fn bind_server<NH, L, F, Fut>(
+    mut listener: L,
+    new_handler: Arc<NH>,
+    mut shutdown: ShutdownSignal,
+    limits: ConnectionLimits,
+    proxy_protocol: bool,
+    finish: F,
+) -> Result<(), ()>
+where
+    NH: NewHandler + 'static,
+    L: Listener + 'static,
+    F: Fn(L::Conn, SocketAddr, Arc<NH>) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let connections = shutdown.connections();
+    let mut drain_timeout = None;
+    // Dropping `abort_connections` resolves `aborted`, which ends every connection still running.
+    let (abort_connections, aborted) = oneshot::channel::<()>();
+    let aborted = aborted.shared();
+
+    loop {
+        // Wait for a connection permit before polling `accept`, so once the limit is reached the
+        // loop stops pulling sockets off the listener instead of accepting them and piling up
+        // work it can't yet serve.
+        let permit = select! {
+            permit = limits.acquire_connection().fuse() => permit,
+            timeout = shutdown.triggered().fuse() => {
+                drain_timeout = timeout;
+                break;
+            },
+        };
+
+        select! {
+            accepted = listener.accept().fuse() => {
+                let mut socket = match accepted {
+                    Ok(socket) => socket,
+                    Err(e) => {
+                        error!(target: "gotham::tls", "Error accepting socket: {:?}", e);
+                        continue;
+                    }
+                };
+
+                let guard = ConnectionGuard::new(connections.clone());
+                let new_handler = new_handler.clone();
+                let finish = finish.clone();
+                let aborted = aborted.clone();
+
+                let connection = async move {
+                    let _guard = guard;
+                    let _permit = permit;
+
+                    let client_addr = if proxy_protocol {
+                        match socket.read_proxy_header().await {
+                            Ok(Some(addr)) => addr,
+                            Ok(None) => {
+                                error!(target: "gotham::tls", "PROXY protocol is not supported on this transport");
+                                return;
+                            }
+                            Err(e) => {
+                                error!(target: "gotham::tls", "PROXY protocol error: {:?}", e);
+                                return;
+                            }
+                        }
+                    } else {
+                        match Connection::peer_addr(&socket) {
+                            Ok(Some(addr)) => addr,
+                            // Transports like Unix domain sockets have no `SocketAddr` to report;
+                            // serve the connection anyway under a placeholder address rather than
+                            // dropping it.
+                            Ok(None) => unknown_peer_addr(),
+                            Err(e) => {
+                                error!(target: "gotham::tls", "Error reading peer address: {:?}", e);
+                                return;
+                            }
+                        }
+                    };
+
+                    finish(socket, client_addr, new_handler).await;
+                };
+                tokio::spawn(async move {
+                    let _ = future::select(Box::pin(connection), aborted).await;
+                });
+            },
+            timeout = shutdown.triggered().fuse() => {
+                drain_timeout = timeout;
+                break;
+            },
+        }
+    }
+
+    drain(connections, drain_timeout).await;
+    drop(abort_connections);
+
+    Ok(())
+}
@@ -0,0 +1,159 @@
+//! Tuning knobs for the HTTP/1 and HTTP/2 protocol implementations `hyper` drives connections
+//! with, so callers aren't stuck with its defaults for keep-alive, header limits, or HTTP/2 flow
+//! control.
+
+use hyper::server::conn::Http;
+use std::time::Duration;
+
+/// HTTP/1 and HTTP/2 protocol options, applied to every connection the server accepts.
+///
+/// Unset options keep `hyper`'s own default. Build one with [`HttpConfig::new`] and the builder
+/// methods below, then pass it to [`super::ServerOptions::http`].
+#[derive(Clone, Copy, Default)]
+pub struct HttpConfig {
+    http1_max_buf_size: Option<usize>,
+    http1_half_close: Option<bool>,
+    http1_title_case_headers: Option<bool>,
+    http1_keep_alive: Option<bool>,
+    http2_initial_stream_window_size: Option<u32>,
+    http2_initial_connection_window_size: Option<u32>,
+    http2_max_concurrent_streams: Option<u32>,
+    http2_adaptive_window: Option<bool>,
+    http2_keep_alive_interval: Option<Duration>,
+    http2_keep_alive_timeout: Option<Duration>,
+}
+
+impl HttpConfig {
+    /// Creates an `HttpConfig` with every option left at `hyper`'s default.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum buffer size for the HTTP/1 connection read/write buffers.
+    pub fn http1_max_buf_size(mut self, max: usize) -> Self {
+        self.http1_max_buf_size = Some(max);
+        self
+    }
+
+    /// Allows the client to half-close its side of an HTTP/1 connection (stop writing but keep
+    /// reading) without the server tearing down the whole connection.
+    pub fn http1_half_close(mut self, enabled: bool) -> Self {
+        self.http1_half_close = Some(enabled);
+        self
+    }
+
+    /// Emits response headers with their original title case (e.g. `Content-Type`) instead of
+    /// lowercase, for clients that are sensitive to it.
+    pub fn http1_title_case_headers(mut self, enabled: bool) -> Self {
+        self.http1_title_case_headers = Some(enabled);
+        self
+    }
+
+    /// Enables or disables HTTP/1 keep-alive.
+    pub fn http1_keep_alive(mut self, enabled: bool) -> Self {
+        self.http1_keep_alive = Some(enabled);
+        self
+    }
+
+    /// Sets the initial HTTP/2 stream-level flow control window size.
+    pub fn http2_initial_stream_window_size(mut self, size: u32) -> Self {
+        self.http2_initial_stream_window_size = Some(size);
+        self
+    }
+
+    /// Sets the initial HTTP/2 connection-level flow control window size.
+    pub fn http2_initial_connection_window_size(mut self, size: u32) -> Self {
+        self.http2_initial_connection_window_size = Some(size);
+        self
+    }
+
+    /// Caps the number of concurrent HTTP/2 streams a single connection may have open.
+    pub fn http2_max_concurrent_streams(mut self, max: u32) -> Self {
+        self.http2_max_concurrent_streams = Some(max);
+        self
+    }
+
+    /// Enables HTTP/2 adaptive flow control, which overrides the fixed window sizes above and
+    /// has `hyper` tune them automatically via BDP estimation.
+    pub fn http2_adaptive_window(mut self, enabled: bool) -> Self {
+        self.http2_adaptive_window = Some(enabled);
+        self
+    }
+
+    /// Sets how often HTTP/2 `PING` frames are sent to keep idle connections alive and detect
+    /// dead ones.
+    pub fn http2_keep_alive_interval(mut self, interval: Duration) -> Self {
+        self.http2_keep_alive_interval = Some(interval);
+        self
+    }
+
+    /// Sets how long the server waits for a `PING` ack before closing an HTTP/2 connection.
+    pub fn http2_keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.http2_keep_alive_timeout = Some(timeout);
+        self
+    }
+
+    /// Builds an `Http` that negotiates either HTTP/1.1 or HTTP/2 depending on what the client
+    /// speaks. Used directly for plain connections, and as the fallback for TLS connections
+    /// where ALPN didn't negotiate `h2`.
+    pub(crate) fn build_auto(&self) -> Http {
+        let mut http = Http::new();
+        self.apply_http1(&mut http);
+        self.apply_http2(&mut http);
+        http
+    }
+
+    /// Builds an `Http` forced to HTTP/2 only, for TLS connections where ALPN negotiated `h2`.
+    pub(crate) fn build_http2_only(&self) -> Http {
+        let mut http = Http::new();
+        http.http2_only(true);
+        self.apply_http2(&mut http);
+        http
+    }
+
+    fn apply_http1(&self, http: &mut Http) {
+        if let Some(max) = self.http1_max_buf_size {
+            http.max_buf_size(max);
+        }
+        if let Some(enabled) = self.http1_half_close {
+            http.http1_half_close(enabled);
+        }
+        if let Some(enabled) = self.http1_title_case_headers {
+            http.http1_title_case_headers(enabled);
+        }
+        if let Some(enabled) = self.http1_keep_alive {
+            http.http1_keep_alive(enabled);
+        }
+    }
+
+    fn apply_http2(&self, http: &mut Http) {
+        if let Some(size) = self.http2_initial_stream_window_size {
+            http.http2_initial_stream_window_size(size);
+        }
+        if let Some(size) = self.http2_initial_connection_window_size {
+            http.http2_initial_connection_window_size(size);
+        }
+        if let Some(max) = self.http2_max_concurrent_streams {
+            http.http2_max_concurrent_streams(max);
+        }
+        if let Some(enabled) = self.http2_adaptive_window {
+            http.http2_adaptive_window(enabled);
+        }
+        if let Some(interval) = self.http2_keep_alive_interval {
+            http.http2_keep_alive_interval(Some(interval));
+        }
+        if let Some(timeout) = self.http2_keep_alive_timeout {
+            http.http2_keep_alive_timeout(timeout);
+        }
+    }
+}
+
+/// The ALPN protocol IDs advertised for a TLS connection, most preferred first, so a negotiating
+/// client may pick HTTP/2 when it supports it.
+///
+/// Applied automatically by [`super::init_server_with_options`] and
+/// [`super::init_server_with_listener`]; callers building their own [`super::ReloadableTlsConfig`]
+/// should set `alpn_protocols` to this on every `rustls::ServerConfig` they build themselves.
+pub fn alpn_protocols() -> Vec<Vec<u8>> {
+    vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+}
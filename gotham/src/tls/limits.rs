@@ -0,0 +1,107 @@
+//! Backpressure limits bounding concurrent connections and in-progress TLS handshakes.
+
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// Caps concurrent connections and concurrent TLS handshakes independently, so a flood of new
+/// sockets or a pile of slow/incomplete handshakes can't exhaust memory.
+#[derive(Clone)]
+pub(crate) struct ConnectionLimits {
+    connections: Arc<Semaphore>,
+    handshakes: Arc<Semaphore>,
+}
+
+impl ConnectionLimits {
+    pub(crate) fn new(max_connections: usize, max_handshakes: usize) -> Self {
+        ConnectionLimits {
+            connections: Arc::new(Semaphore::new(max_connections)),
+            handshakes: Arc::new(Semaphore::new(max_handshakes)),
+        }
+    }
+
+    /// Waits for a connection permit to become free. The accept loop awaits this before calling
+    /// `Listener::accept`, so once the limit is reached it stops polling for new sockets instead
+    /// of accepting them and then queuing up work it can't yet serve.
+    pub(crate) async fn acquire_connection(&self) -> ConnectionPermit {
+        let permit = self.connections.acquire().await;
+        permit.forget();
+        ConnectionPermit(self.connections.clone())
+    }
+
+    /// Waits for a handshake permit to become free, bounding concurrent in-progress TLS
+    /// handshakes independently of the connection limit above.
+    pub(crate) async fn acquire_handshake(&self) -> HandshakePermit {
+        let permit = self.handshakes.acquire().await;
+        permit.forget();
+        HandshakePermit(self.handshakes.clone())
+    }
+}
+
+/// RAII guard for a connection permit; releases it back to the semaphore on drop.
+pub(crate) struct ConnectionPermit(Arc<Semaphore>);
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.0.add_permits(1);
+    }
+}
+
+/// RAII guard for a handshake permit; releases it back to the semaphore on drop.
+pub(crate) struct HandshakePermit(Arc<Semaphore>);
+
+impl Drop for HandshakePermit {
+    fn drop(&mut self) {
+        self.0.add_permits(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::{self, Either};
+    use std::time::Duration;
+    use tokio::time::delay_for;
+
+    #[tokio::test]
+    async fn acquire_connection_blocks_once_max_connections_is_reached() {
+        let limits = ConnectionLimits::new(1, 1);
+        let _permit = limits.acquire_connection().await;
+
+        let timed_out = future::select(
+            Box::pin(limits.acquire_connection()),
+            Box::pin(delay_for(Duration::from_millis(20))),
+        )
+        .await;
+        assert!(matches!(timed_out, Either::Right(_)));
+    }
+
+    #[tokio::test]
+    async fn dropping_a_connection_permit_releases_it_back() {
+        let limits = ConnectionLimits::new(1, 1);
+        let permit = limits.acquire_connection().await;
+        drop(permit);
+
+        // With the only permit released, the next acquire resolves right away instead of
+        // timing out.
+        let timed_out = future::select(
+            Box::pin(limits.acquire_connection()),
+            Box::pin(delay_for(Duration::from_millis(20))),
+        )
+        .await;
+        assert!(matches!(timed_out, Either::Left(_)));
+    }
+
+    #[tokio::test]
+    async fn acquire_handshake_is_bounded_independently_of_connections() {
+        let limits = ConnectionLimits::new(1, 1);
+        let _connection_permit = limits.acquire_connection().await;
+
+        // The connection limit is exhausted, but handshakes are capped by a separate semaphore.
+        let timed_out = future::select(
+            Box::pin(limits.acquire_handshake()),
+            Box::pin(delay_for(Duration::from_millis(20))),
+        )
+        .await;
+        assert!(matches!(timed_out, Either::Left(_)));
+    }
+}
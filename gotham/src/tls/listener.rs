@@ -0,0 +1,93 @@
+//! A transport-agnostic accept loop, so the server isn't hardcoded to `TcpListener`.
+
+use async_trait::async_trait;
+use futures::future::{self, BoxFuture};
+use std::io;
+use std::net::SocketAddr;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+
+use super::proxy_protocol;
+
+/// A transport that accepts incoming connections, analogous to `tokio::net::TcpListener` but
+/// generic enough to cover Unix domain sockets or an entirely custom (e.g. in-process) stream.
+#[async_trait]
+pub trait Listener: Send {
+    /// The connection type this listener produces.
+    type Conn: Connection;
+
+    /// Accepts the next incoming connection.
+    async fn accept(&mut self) -> io::Result<Self::Conn>;
+
+    /// Returns the address this listener is bound to, for transports that have one. Unix
+    /// sockets and custom in-process transports may not.
+    fn local_addr(&self) -> io::Result<Option<SocketAddr>>;
+}
+
+/// A single accepted connection: a duplex byte stream, plus whatever address information the
+/// transport can provide about the remote peer.
+pub trait Connection: AsyncRead + AsyncWrite + Send + Unpin + 'static {
+    /// Returns the remote peer's address, for transports that have one.
+    fn peer_addr(&self) -> io::Result<Option<SocketAddr>>;
+
+    /// Attempts to strip a PROXY protocol header from the start of this connection.
+    ///
+    /// Returns `Ok(None)` when the transport doesn't support PROXY protocol at all — the
+    /// default for anything other than TCP, since the protocol only makes sense behind an L4
+    /// load balancer. `TcpStream` overrides this to actually sniff and consume the header.
+    fn read_proxy_header(&mut self) -> BoxFuture<'_, io::Result<Option<SocketAddr>>> {
+        Box::pin(future::ready(Ok(None)))
+    }
+}
+
+#[async_trait]
+impl Listener for TcpListener {
+    type Conn = TcpStream;
+
+    async fn accept(&mut self) -> io::Result<Self::Conn> {
+        let (socket, _) = TcpListener::accept(self).await?;
+        Ok(socket)
+    }
+
+    fn local_addr(&self) -> io::Result<Option<SocketAddr>> {
+        TcpListener::local_addr(self).map(Some)
+    }
+}
+
+impl Connection for TcpStream {
+    fn peer_addr(&self) -> io::Result<Option<SocketAddr>> {
+        TcpStream::peer_addr(self).map(Some)
+    }
+
+    fn read_proxy_header(&mut self) -> BoxFuture<'_, io::Result<Option<SocketAddr>>> {
+        Box::pin(async move {
+            let proxy_protocol::ClientAddr(addr) = proxy_protocol::read_proxy_header(self).await?;
+            Ok(Some(addr))
+        })
+    }
+}
+
+#[cfg(unix)]
+#[async_trait]
+impl Listener for UnixListener {
+    type Conn = UnixStream;
+
+    async fn accept(&mut self) -> io::Result<Self::Conn> {
+        let (socket, _) = UnixListener::accept(self).await?;
+        Ok(socket)
+    }
+
+    fn local_addr(&self) -> io::Result<Option<SocketAddr>> {
+        // Unix domain sockets aren't identified by a `SocketAddr`.
+        Ok(None)
+    }
+}
+
+#[cfg(unix)]
+impl Connection for UnixStream {
+    fn peer_addr(&self) -> io::Result<Option<SocketAddr>> {
+        Ok(None)
+    }
+}
@@ -0,0 +1,398 @@
+use futures::prelude::*;
+use futures_rustls::rustls::Session;
+use futures_rustls::{rustls, TlsAcceptor};
+use log::{error, info};
+use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::runtime::TaskExecutor;
+
+use super::{new_runtime, tcp_listener};
+
+use super::handler::NewHandler;
+use super::service::GothamService;
+
+pub(crate) mod accept;
+mod http_config;
+pub(crate) mod limits;
+pub mod listener;
+pub mod multi;
+pub mod proxy_protocol;
+pub mod redirect;
+pub mod reload;
+pub mod shutdown;
+pub mod test;
+
+pub use self::http_config::{alpn_protocols, HttpConfig};
+pub use self::listener::{Connection, Listener};
+pub use self::multi::{serve_all, MultiHandle, Server};
+pub use self::redirect::redirect_to_https;
+pub use self::reload::{ReloadHandle, ReloadableTlsConfig};
+pub use self::shutdown::Handle;
+
+use self::limits::ConnectionLimits;
+use self::shutdown::ShutdownSignal;
+
+/// Default cap on concurrent connections, as a multiple of the number of CPUs.
+const DEFAULT_CONNECTIONS_PER_CPU: usize = 256;
+
+/// Default cap on concurrent in-progress TLS handshakes, as a multiple of the number of CPUs.
+const DEFAULT_HANDSHAKES_PER_CPU: usize = 64;
+
+/// Placeholder client address used for transports (e.g. Unix domain sockets) whose `Connection`
+/// has no real `SocketAddr` to report.
+fn unknown_peer_addr() -> SocketAddr {
+    SocketAddr::from((Ipv4Addr::new(0, 0, 0, 0), 0))
+}
+
+/// Supplies the `rustls::ServerConfig` to use for the next accepted connection.
+///
+/// Implemented for a fixed `Arc<rustls::ServerConfig>` and for [`ReloadableTlsConfig`], so
+/// `bind_server_rustls` can read the current config per-connection without caring which kind of
+/// source it was given.
+trait TlsConfigSource: Clone + Send + 'static {
+    fn current(&self) -> Arc<rustls::ServerConfig>;
+}
+
+impl TlsConfigSource for Arc<rustls::ServerConfig> {
+    fn current(&self) -> Arc<rustls::ServerConfig> {
+        self.clone()
+    }
+}
+
+impl TlsConfigSource for ReloadableTlsConfig {
+    fn current(&self) -> Arc<rustls::ServerConfig> {
+        ReloadableTlsConfig::current(self)
+    }
+}
+
+/// Advanced, opt-in options for starting a TLS server, beyond what `start`/`init_server`
+/// expose directly.
+#[derive(Clone)]
+pub struct ServerOptions {
+    proxy_protocol: bool,
+    max_connections: usize,
+    max_handshakes: usize,
+    http: HttpConfig,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        let cpus = num_cpus::get();
+        ServerOptions {
+            proxy_protocol: false,
+            max_connections: cpus * DEFAULT_CONNECTIONS_PER_CPU,
+            max_handshakes: cpus * DEFAULT_HANDSHAKES_PER_CPU,
+            http: HttpConfig::default(),
+        }
+    }
+}
+
+impl ServerOptions {
+    /// Creates a `ServerOptions` with every option at its default setting.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// When enabled, each accepted connection is expected to begin with a PROXY protocol v1/v2
+    /// header (as sent by HAProxy/AWS NLB) carrying the real client address. The header is
+    /// stripped before the socket is handed to TLS, and the decoded address is made available
+    /// to handlers as a `proxy_protocol::ClientAddr`. Connections with a missing or malformed
+    /// header are rejected.
+    pub fn proxy_protocol(mut self, enabled: bool) -> Self {
+        self.proxy_protocol = enabled;
+        self
+    }
+
+    /// Caps the number of connections served concurrently. Once the limit is reached, the
+    /// accept loop stops polling for new sockets until an existing connection finishes, instead
+    /// of accepting unboundedly and piling up work it can't yet serve. Defaults to a multiple of
+    /// the number of CPUs.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = max;
+        self
+    }
+
+    /// Caps the number of TLS handshakes in progress concurrently, independently of
+    /// `max_connections`, so a flood of slow or incomplete handshakes can't exhaust memory on
+    /// their own. Defaults to a multiple of the number of CPUs.
+    pub fn max_handshakes(mut self, max: usize) -> Self {
+        self.max_handshakes = max;
+        self
+    }
+
+    /// Sets the HTTP/1 and HTTP/2 protocol tuning `hyper` uses for every accepted connection. The
+    /// protocols advertised via ALPN are not affected by this; they come from [`alpn_protocols`].
+    pub fn http(mut self, http: HttpConfig) -> Self {
+        self.http = http;
+        self
+    }
+}
+
+/// Starts a Gotham application with the default number of threads.
+pub fn start<NH, A>(addr: A, new_handler: NH, tls_config: rustls::ServerConfig)
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs + 'static,
+{
+    start_with_num_threads(addr, new_handler, tls_config, num_cpus::get())
+}
+
+/// Starts a Gotham application with a designated number of threads.
+pub fn start_with_num_threads<NH, A>(
+    addr: A,
+    new_handler: NH,
+    tls_config: rustls::ServerConfig,
+    threads: usize,
+) where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs + 'static,
+{
+    let runtime = new_runtime(threads);
+    start_on_executor(addr, new_handler, tls_config, runtime.executor());
+    runtime.shutdown_on_idle();
+}
+
+/// Starts a Gotham application with a designated backing `TaskExecutor`.
+///
+/// This function can be used to spawn the server on an existing `Runtime`.
+pub fn start_on_executor<NH, A>(
+    addr: A,
+    new_handler: NH,
+    tls_config: rustls::ServerConfig,
+    executor: TaskExecutor,
+) where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs + 'static,
+{
+    executor.spawn(init_server(addr, new_handler, tls_config));
+}
+
+/// Starts a Gotham application on a designated backing `TaskExecutor`, returning a [`Handle`]
+/// that can be used to trigger a graceful shutdown of the server.
+///
+/// Wire this up to `tokio::signal` (e.g. SIGTERM) to drain in-flight requests deterministically
+/// instead of killing the process outright.
+pub fn start_with_handle<NH, A>(
+    addr: A,
+    new_handler: NH,
+    tls_config: rustls::ServerConfig,
+    executor: TaskExecutor,
+) -> Handle
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs + 'static,
+{
+    let (future, handle) = init_server_with_handle(addr, new_handler, tls_config);
+    executor.spawn(future);
+    handle
+}
+
+/// Returns a `Future` used to spawn an Gotham application.
+///
+/// This is used internally, but exposed in case the developer intends on doing any
+/// manual wiring that isn't supported by the Gotham API. It's unlikely that this will
+/// be required in most use cases; it's mainly exposed for shutdown handling.
+pub fn init_server<NH, A>(
+    addr: A,
+    new_handler: NH,
+    tls_config: rustls::ServerConfig,
+) -> impl Future<Output = ()>
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs + 'static,
+{
+    let (future, _handle) = init_server_with_handle(addr, new_handler, tls_config);
+    future
+}
+
+/// Like [`init_server`], but also returns a [`Handle`] which can be used to trigger a graceful
+/// shutdown: stop accepting new connections, drain the connections already in flight, then
+/// resolve the serving future.
+pub fn init_server_with_handle<NH, A>(
+    addr: A,
+    new_handler: NH,
+    tls_config: rustls::ServerConfig,
+) -> (impl Future<Output = ()>, Handle)
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs + 'static,
+{
+    init_server_with_options(addr, new_handler, tls_config, ServerOptions::default())
+}
+
+/// Like [`init_server_with_handle`], but with [`ServerOptions`] controlling the advanced,
+/// opt-in behaviour of the accept loop (e.g. PROXY protocol support).
+pub fn init_server_with_options<NH, A>(
+    addr: A,
+    new_handler: NH,
+    mut tls_config: rustls::ServerConfig,
+    options: ServerOptions,
+) -> (impl Future<Output = ()>, Handle)
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs + 'static,
+{
+    if tls_config.alpn_protocols.is_empty() {
+        tls_config.alpn_protocols = alpn_protocols();
+    }
+    init_server_with_tls_source(addr, new_handler, Arc::new(tls_config), options)
+}
+
+/// Like [`init_server_with_options`], but backed by a [`ReloadableTlsConfig`] whose certificate
+/// can be swapped out (via its [`ReloadHandle`]) without dropping the listener.
+///
+/// Unlike [`init_server_with_options`], the `ServerConfig`s behind `tls_config` are built by the
+/// caller (initially and on every [`ReloadHandle::reload`]), so ALPN isn't set automatically
+/// here; set each one's `alpn_protocols` to [`alpn_protocols()`] yourself if you want HTTP/2
+/// negotiated over TLS.
+pub fn init_server_with_reloadable_config<NH, A>(
+    addr: A,
+    new_handler: NH,
+    tls_config: ReloadableTlsConfig,
+    options: ServerOptions,
+) -> (impl Future<Output = ()>, Handle)
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs + 'static,
+{
+    init_server_with_tls_source(addr, new_handler, tls_config, options)
+}
+
+fn init_server_with_tls_source<NH, A, C>(
+    addr: A,
+    new_handler: NH,
+    tls_config: C,
+    options: ServerOptions,
+) -> (impl Future<Output = ()>, Handle)
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs + 'static,
+    C: TlsConfigSource,
+{
+    let (handle, signal) = Handle::new();
+
+    let future = tcp_listener(addr)
+        .map_err(|_| ())
+        .and_then(|listener| serve(listener, new_handler, tls_config, signal, options).map_err(|_| ()))
+        .then(|_| future::ready(())); // Ignore the result
+
+    (future, handle)
+}
+
+/// Starts a Gotham application on an already-bound [`Listener`] — a Unix domain socket, a
+/// custom in-process transport, or a TCP listener obtained some other way than via `start`'s
+/// `ToSocketAddrs`. Returns a [`Handle`] for graceful shutdown, same as [`init_server_with_handle`].
+pub fn init_server_with_listener<NH, L>(
+    listener: L,
+    new_handler: NH,
+    mut tls_config: rustls::ServerConfig,
+    options: ServerOptions,
+) -> (impl Future<Output = ()>, Handle)
+where
+    NH: NewHandler + 'static,
+    L: Listener + 'static,
+{
+    if tls_config.alpn_protocols.is_empty() {
+        tls_config.alpn_protocols = alpn_protocols();
+    }
+    let (handle, signal) = Handle::new();
+    let future = serve(listener, new_handler, Arc::new(tls_config), signal, options)
+        .map_err(|_| ())
+        .then(|_| future::ready(()));
+    (future, handle)
+}
+
+/// Starts a Gotham application listening on a Unix domain socket rather than TCP.
+#[cfg(unix)]
+pub fn init_server_with_unix_listener<NH>(
+    listener: UnixListener,
+    new_handler: NH,
+    tls_config: rustls::ServerConfig,
+) -> (impl Future<Output = ()>, Handle)
+where
+    NH: NewHandler + 'static,
+{
+    init_server_with_listener(listener, new_handler, tls_config, ServerOptions::default())
+}
+
+fn serve<NH, C, L>(
+    listener: L,
+    new_handler: NH,
+    tls_config: C,
+    signal: ShutdownSignal,
+    options: ServerOptions,
+) -> impl Future<Output = Result<(), ()>>
+where
+    NH: NewHandler + 'static,
+    C: TlsConfigSource,
+    L: Listener + 'static,
+{
+    if let Ok(Some(addr)) = Listener::local_addr(&listener) {
+        info!(target: "gotham::start", " Gotham listening on http://{}", addr);
+    } else {
+        info!(target: "gotham::start", " Gotham listening");
+    }
+
+    bind_server_rustls(listener, new_handler, tls_config, signal, options)
+}
+
+async fn bind_server_rustls<NH, C, L>(
+    listener: L,
+    new_handler: NH,
+    tls_config: C,
+    shutdown: ShutdownSignal,
+    options: ServerOptions,
+) -> Result<(), ()>
+where
+    NH: NewHandler + 'static,
+    C: TlsConfigSource,
+    L: Listener + 'static,
+{
+    let protocol = Arc::new(options.http.build_auto());
+    let protocol_h2 = Arc::new(options.http.build_http2_only());
+    let new_handler = Arc::new(new_handler);
+    let limits = ConnectionLimits::new(options.max_connections, options.max_handshakes);
+    let handshake_limits = limits.clone();
+
+    accept::bind_server(
+        listener,
+        new_handler,
+        shutdown,
+        limits,
+        options.proxy_protocol,
+        move |socket, client_addr, new_handler| {
+            let tls = TlsAcceptor::from(tls_config.current());
+            let protocol = protocol.clone();
+            let protocol_h2 = protocol_h2.clone();
+            let limits = handshake_limits.clone();
+
+            async move {
+                let handshake_permit = limits.acquire_handshake().await;
+                let tls_stream = match tls.accept(futures_tokio_compat::Compat::new(socket)).await {
+                    Ok(tls_stream) => tls_stream,
+                    Err(e) => {
+                        error!(target: "gotham::tls", "TLS handshake error: {:?}", e);
+                        return;
+                    }
+                };
+                drop(handshake_permit);
+
+                // If ALPN picked `h2` during the handshake above, drive this connection with the
+                // HTTP/2-only builder; otherwise fall back to the auto-negotiating one.
+                let negotiated_h2 = tls_stream.get_ref().1.get_alpn_protocol() == Some(&b"h2"[..]);
+                let protocol = if negotiated_h2 { &protocol_h2 } else { &protocol };
+                let socket = futures_tokio_compat::Compat::new(tls_stream);
+
+                let new_handler = proxy_protocol::with_client_addr(client_addr, new_handler);
+                let service = GothamService::connect(client_addr, new_handler);
+                if let Err(e) = protocol.serve_connection(socket, service).await {
+                    error!(target: "gotham::tls", "Connection error: {:?}", e);
+                }
+            }
+        },
+    )
+    .await
+}
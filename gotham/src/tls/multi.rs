@@ -0,0 +1,46 @@
+//! Running several listeners — e.g. a plain HTTP redirect on `:80` alongside TLS HTTPS on `:443`
+//! — against the same handler, concurrently, on one runtime.
+
+use futures::future::{join_all, BoxFuture, FutureExt};
+use std::time::Duration;
+
+use super::shutdown::Handle;
+
+/// One already-started server: the `Future` returned by an `init_server*` function (TLS or
+/// plain), boxed so servers of different concrete types can be driven side by side, paired with
+/// the [`Handle`] used to shut it down.
+pub type Server = (BoxFuture<'static, ()>, Handle);
+
+/// Drives several already-started servers concurrently, resolving once every one of them has
+/// stopped. Use this to run a plain HTTP listener (e.g. redirecting to HTTPS via
+/// [`super::redirect::redirect_to_https`]) alongside a TLS listener on the same runtime.
+///
+/// Returns a [`MultiHandle`] that triggers a graceful shutdown of every listener in the set
+/// together.
+pub fn serve_all(servers: Vec<Server>) -> (impl std::future::Future<Output = ()>, MultiHandle) {
+    let (futures, handles): (Vec<_>, Vec<_>) = servers.into_iter().unzip();
+    let future = join_all(futures).map(|_| ());
+    (future, MultiHandle { handles })
+}
+
+/// A [`Handle`] over every listener started together via [`serve_all`].
+pub struct MultiHandle {
+    handles: Vec<Handle>,
+}
+
+impl MultiHandle {
+    /// Triggers a graceful shutdown of every listener in the set: each stops accepting new
+    /// connections immediately, and the future returned by `serve_all` resolves once all of them
+    /// have drained their in-flight connections (or `timeout` elapses for each).
+    pub fn shutdown(self, timeout: Option<Duration>) {
+        for handle in self.handles {
+            handle.shutdown(timeout);
+        }
+    }
+
+    /// Returns the total number of connections currently being served, summed across every
+    /// listener in the set.
+    pub fn connection_count(&self) -> usize {
+        self.handles.iter().map(Handle::connection_count).sum()
+    }
+}
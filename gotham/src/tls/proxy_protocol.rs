@@ -0,0 +1,351 @@
+//! Support for the HAProxy/AWS-NLB "PROXY protocol", used to recover the real client address
+//! when Gotham sits behind an L4 load balancer instead of seeing the balancer's own address.
+
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+
+use crate::handler::{Handler, HandlerFuture, NewHandler};
+use crate::state::{State, StateData};
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+const V1_MAX_LEN: usize = 107;
+
+const V2_SIGNATURE: [u8; 12] = [
+    0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A,
+];
+
+// A header this small should arrive in full almost immediately; callers behind a segmenting
+// proxy get a little slack via short retries, but `read_proxy_header`'s overall deadline (below)
+// is what actually bounds how long a connection can hold a permit open waiting for one.
+const PEEK_RETRY_DELAY: Duration = Duration::from_millis(10);
+
+/// Overall deadline for receiving and parsing a PROXY protocol preamble. Bounds how long a
+/// connection permit can be tied up by a client that connects and then sends nothing (or sends
+/// its header one byte at a time), regardless of how many retries that takes.
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The largest v2 address block we know how to parse: a v2 header's `len` field is attacker
+/// controlled (up to 65535), but the only address families we decode are AF_INET (12 bytes) and
+/// AF_INET6 (36 bytes), so anything claiming to be bigger than that is either a protocol we don't
+/// support (TLVs) or padding — either way not worth allocating and reading in full.
+const V2_MAX_ADDR_LEN: usize = 36;
+
+/// Peeks at `socket` until `buf` is filled or `condition` is satisfied by however much has
+/// arrived so far. Needed because a single `peek` only returns whatever's already in the kernel's
+/// receive buffer, which under TCP segmentation can be less than the whole PROXY header. Bounded
+/// by the deadline `read_proxy_header` wraps its caller in, not by an attempt count of its own.
+async fn peek_until(
+    socket: &TcpStream,
+    buf: &mut [u8],
+    mut condition: impl FnMut(&[u8]) -> bool,
+) -> io::Result<usize> {
+    let mut n = socket.peek(buf).await?;
+    while n < buf.len() && !condition(&buf[..n]) {
+        tokio::time::delay_for(PEEK_RETRY_DELAY).await;
+        n = socket.peek(buf).await?;
+    }
+    Ok(n)
+}
+
+/// The client address of the connection a request arrived on, made available in `State`. When
+/// PROXY protocol is enabled this is the real client address recovered from the header, in place
+/// of the load balancer's own socket address; otherwise it is the connection's own peer address
+/// (`0.0.0.0:0` for transports such as Unix domain sockets that have none).
+#[derive(Debug, Clone, Copy)]
+pub struct ClientAddr(pub SocketAddr);
+
+impl StateData for ClientAddr {}
+
+/// Wraps `new_handler` so every request served on this connection sees `client_addr` in its
+/// `State` as a [`ClientAddr`] — the address recovered from a PROXY protocol header, or just the
+/// connection's own peer address when the header wasn't required.
+pub(crate) fn with_client_addr<NH>(
+    client_addr: SocketAddr,
+    new_handler: Arc<NH>,
+) -> Arc<WithClientAddr<NH>> {
+    Arc::new(WithClientAddr {
+        client_addr: ClientAddr(client_addr),
+        inner: new_handler,
+    })
+}
+
+/// A [`NewHandler`] that inserts a [`ClientAddr`] into `State` ahead of an inner handler. Built
+/// via [`with_client_addr`].
+pub(crate) struct WithClientAddr<NH> {
+    client_addr: ClientAddr,
+    inner: Arc<NH>,
+}
+
+impl<NH: NewHandler> NewHandler for WithClientAddr<NH> {
+    type Instance = WithClientAddrHandler<NH::Instance>;
+
+    fn new_handler(&self) -> anyhow::Result<Self::Instance> {
+        Ok(WithClientAddrHandler {
+            client_addr: self.client_addr,
+            inner: self.inner.new_handler()?,
+        })
+    }
+}
+
+/// The [`Handler`] behind [`WithClientAddr`].
+pub(crate) struct WithClientAddrHandler<H> {
+    client_addr: ClientAddr,
+    inner: H,
+}
+
+impl<H: Handler> Handler for WithClientAddrHandler<H> {
+    fn handle(self, mut state: State) -> Pin<Box<HandlerFuture>> {
+        state.put(self.client_addr);
+        self.inner.handle(state)
+    }
+}
+
+/// Peeks the start of `socket` for a PROXY protocol v1 or v2 header, consumes it, and returns
+/// the client address it encodes. Connections that don't start with a recognised header, whose
+/// header is truncated or malformed, or that don't finish sending one within
+/// [`PROXY_HEADER_TIMEOUT`], are rejected with an error.
+pub(crate) async fn read_proxy_header(socket: &mut TcpStream) -> io::Result<ClientAddr> {
+    tokio::time::timeout(PROXY_HEADER_TIMEOUT, read_proxy_header_inner(socket))
+        .await
+        .unwrap_or_else(|_| Err(invalid_data("timed out waiting for a PROXY protocol header")))
+}
+
+async fn read_proxy_header_inner(socket: &mut TcpStream) -> io::Result<ClientAddr> {
+    let mut peeked = [0u8; V2_SIGNATURE.len()];
+    // The v2 signature is the longer of the two prefixes we recognise, so waiting for the whole
+    // buffer (or a mismatch we can already tell from a shorter prefix) covers both cases.
+    let n = peek_until(socket, &mut peeked, |got| {
+        !V2_SIGNATURE.starts_with(got) && got.len() >= V1_PREFIX.len()
+    })
+    .await?;
+
+    let addr = if n >= V2_SIGNATURE.len() && peeked == V2_SIGNATURE {
+        read_v2(socket).await?
+    } else if n >= V1_PREFIX.len() && peeked[..V1_PREFIX.len()] == *V1_PREFIX {
+        read_v1(socket).await?
+    } else {
+        return Err(invalid_data("connection did not start with a PROXY protocol header"));
+    };
+
+    Ok(ClientAddr(addr))
+}
+
+async fn read_v1(socket: &mut TcpStream) -> io::Result<SocketAddr> {
+    let mut buf = [0u8; V1_MAX_LEN];
+    // Keep peeking until the terminating CRLF shows up, so a line split across TCP segments
+    // isn't mistaken for one missing its terminator.
+    let n = peek_until(socket, &mut buf, |got| got.windows(2).any(|w| w == b"\r\n")).await?;
+
+    let end = buf[..n]
+        .windows(2)
+        .position(|w| w == b"\r\n")
+        .map(|i| i + 2)
+        .ok_or_else(|| invalid_data("PROXY v1 header is missing a terminating CRLF"))?;
+
+    // Actually consume the bytes we just peeked, so the rest of the socket starts at the payload.
+    let mut header = vec![0u8; end];
+    socket.read_exact(&mut header).await?;
+
+    let line = std::str::from_utf8(&header[..end - 2])
+        .map_err(|_| invalid_data("PROXY v1 header is not valid UTF-8"))?;
+
+    parse_v1_line(line)
+}
+
+fn parse_v1_line(line: &str) -> io::Result<SocketAddr> {
+    let mut parts = line.split(' ');
+
+    match parts.next() {
+        Some("PROXY") => {}
+        _ => return Err(invalid_data("PROXY v1 header missing PROXY prefix")),
+    }
+
+    let proto = parts
+        .next()
+        .ok_or_else(|| invalid_data("PROXY v1 header missing protocol field"))?;
+    if proto == "UNKNOWN" {
+        return Err(invalid_data("PROXY v1 UNKNOWN connections are not supported"));
+    }
+    if proto != "TCP4" && proto != "TCP6" {
+        return Err(invalid_data("PROXY v1 header has an unrecognised protocol"));
+    }
+
+    let src_ip = parts
+        .next()
+        .ok_or_else(|| invalid_data("PROXY v1 header missing source address"))?;
+    let _dst_ip = parts
+        .next()
+        .ok_or_else(|| invalid_data("PROXY v1 header missing destination address"))?;
+    let src_port = parts
+        .next()
+        .ok_or_else(|| invalid_data("PROXY v1 header missing source port"))?;
+
+    let ip = src_ip
+        .parse()
+        .map_err(|_| invalid_data("PROXY v1 header has an invalid source address"))?;
+    let port = src_port
+        .parse()
+        .map_err(|_| invalid_data("PROXY v1 header has an invalid source port"))?;
+
+    Ok(SocketAddr::new(ip, port))
+}
+
+async fn read_v2(socket: &mut TcpStream) -> io::Result<SocketAddr> {
+    let mut header = [0u8; 16];
+    socket.read_exact(&mut header).await?;
+
+    let version = header[12] >> 4;
+    if version != 0x2 {
+        return Err(invalid_data("unsupported PROXY protocol version"));
+    }
+
+    let command = header[12] & 0x0F;
+    let family = header[13] >> 4;
+    let len = u16::from_be_bytes([header[14], header[15]]) as usize;
+
+    if len > V2_MAX_ADDR_LEN {
+        return Err(invalid_data("PROXY v2 header address block is larger than any family we support"));
+    }
+
+    let mut addr_block = vec![0u8; len];
+    socket.read_exact(&mut addr_block).await?;
+
+    // A LOCAL connection (e.g. a load balancer health check) carries no real client address —
+    // its family is AF_UNSPEC and the address block may be empty. Per spec, fall back to the
+    // real connection's own peer address instead of trying to parse one out.
+    if command == 0x0 {
+        return socket.peer_addr();
+    }
+
+    match family {
+        // AF_INET
+        0x1 if addr_block.len() >= 12 => {
+            let ip = Ipv4Addr::new(addr_block[0], addr_block[1], addr_block[2], addr_block[3]);
+            let port = u16::from_be_bytes([addr_block[8], addr_block[9]]);
+            Ok(SocketAddr::from((ip, port)))
+        }
+        // AF_INET6
+        0x2 if addr_block.len() >= 36 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&addr_block[..16]);
+            let ip = Ipv6Addr::from(octets);
+            let port = u16::from_be_bytes([addr_block[32], addr_block[33]]);
+            Ok(SocketAddr::from((ip, port)))
+        }
+        _ => Err(invalid_data("PROXY v2 header has an unsupported or truncated address block")),
+    }
+}
+
+fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn parse_v1_line_tcp4() {
+        let addr = parse_v1_line("PROXY TCP4 192.168.0.1 192.168.0.11 56324 443").unwrap();
+        assert_eq!(addr, SocketAddr::from(([192, 168, 0, 1], 56324)));
+    }
+
+    #[test]
+    fn parse_v1_line_tcp6() {
+        let addr = parse_v1_line("PROXY TCP6 ::1 ::1 56324 443").unwrap();
+        assert_eq!(addr, SocketAddr::new("::1".parse().unwrap(), 56324));
+    }
+
+    #[test]
+    fn parse_v1_line_rejects_unknown_protocol() {
+        assert!(parse_v1_line("PROXY UNKNOWN").is_err());
+    }
+
+    #[test]
+    fn parse_v1_line_rejects_missing_prefix() {
+        assert!(parse_v1_line("TCP4 192.168.0.1 192.168.0.11 56324 443").is_err());
+    }
+
+    #[test]
+    fn parse_v1_line_rejects_truncated_line() {
+        assert!(parse_v1_line("PROXY TCP4 192.168.0.1").is_err());
+    }
+
+    /// Connects a client/server TCP pair over loopback, so `read_v2` can be exercised against a
+    /// real `TcpStream` the way the accept loop uses it.
+    async fn connected_pair() -> (TcpStream, TcpStream) {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = TcpStream::connect(addr).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        (client, server)
+    }
+
+    #[tokio::test]
+    async fn read_v2_parses_proxy_command_over_ipv4() {
+        let (mut client, mut server) = connected_pair().await;
+
+        let mut header = V2_SIGNATURE.to_vec();
+        header.push(0x21); // version 2, command PROXY
+        header.push(0x11); // family AF_INET, protocol STREAM
+        header.extend_from_slice(&12u16.to_be_bytes());
+        header.extend_from_slice(&[10, 0, 0, 1]); // source address
+        header.extend_from_slice(&[10, 0, 0, 2]); // destination address
+        header.extend_from_slice(&12345u16.to_be_bytes()); // source port
+        header.extend_from_slice(&443u16.to_be_bytes()); // destination port
+        client.write_all(&header).await.unwrap();
+
+        let addr = read_v2(&mut server).await.unwrap();
+        assert_eq!(addr, SocketAddr::from(([10, 0, 0, 1], 12345)));
+    }
+
+    #[tokio::test]
+    async fn read_v2_local_command_falls_back_to_peer_addr() {
+        let (mut client, mut server) = connected_pair().await;
+        let expected = server.peer_addr().unwrap();
+
+        let mut header = V2_SIGNATURE.to_vec();
+        header.push(0x20); // version 2, command LOCAL
+        header.push(0x00); // family AF_UNSPEC
+        header.extend_from_slice(&0u16.to_be_bytes());
+        client.write_all(&header).await.unwrap();
+
+        let addr = read_v2(&mut server).await.unwrap();
+        assert_eq!(addr, expected);
+    }
+
+    #[tokio::test]
+    async fn read_v2_rejects_oversized_address_block() {
+        let (mut client, mut server) = connected_pair().await;
+
+        let mut header = V2_SIGNATURE.to_vec();
+        header.push(0x21); // version 2, command PROXY
+        header.push(0x11); // family AF_INET
+        header.extend_from_slice(&(V2_MAX_ADDR_LEN as u16 + 1).to_be_bytes());
+        client.write_all(&header).await.unwrap();
+
+        let err = read_v2(&mut server).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn read_v2_rejects_unsupported_version() {
+        let (mut client, mut server) = connected_pair().await;
+
+        let mut header = V2_SIGNATURE.to_vec();
+        header.push(0x11); // version 1 (unsupported), command PROXY
+        header.push(0x11);
+        header.extend_from_slice(&0u16.to_be_bytes());
+        client.write_all(&header).await.unwrap();
+
+        let err = read_v2(&mut server).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
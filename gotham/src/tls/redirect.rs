@@ -0,0 +1,141 @@
+//! A ready-made handler that 301-redirects plain HTTP requests to their HTTPS equivalent, for
+//! pairing a plain listener with a TLS one via [`super::multi::serve_all`].
+
+use futures::future::{self, FutureExt};
+use hyper::header::{HOST, LOCATION};
+use hyper::{Body, HeaderMap, Response, StatusCode, Uri};
+use std::pin::Pin;
+
+use crate::handler::{Handler, HandlerFuture, NewHandler};
+use crate::state::{FromState, State};
+
+/// Builds a [`NewHandler`] that redirects every request it receives from `http://host/path` to
+/// `https://host:https_port/path` (or `https://host/path` when `https_port` is 443), preserving
+/// the path and query string. Pair it with a plain
+/// HTTP listener alongside a TLS one started via [`super::multi::serve_all`] so clients that
+/// connect on the plain port (e.g. `:80`) get bounced onto the encrypted one (e.g. `:443`).
+///
+/// The target host is taken from the request URI when it is absolute, otherwise from the
+/// request's `Host` header; requests with neither are rejected with `400 Bad Request` rather
+/// than redirected to an unusable URL.
+pub fn redirect_to_https(https_port: u16) -> impl NewHandler<Instance = RedirectHandler> {
+    RedirectHandler { https_port }
+}
+
+/// The [`Handler`]/[`NewHandler`] created by [`redirect_to_https`].
+#[derive(Clone, Copy)]
+pub struct RedirectHandler {
+    https_port: u16,
+}
+
+impl NewHandler for RedirectHandler {
+    type Instance = Self;
+
+    fn new_handler(&self) -> anyhow::Result<Self::Instance> {
+        Ok(*self)
+    }
+}
+
+impl Handler for RedirectHandler {
+    fn handle(self, state: State) -> Pin<Box<HandlerFuture>> {
+        let response = {
+            let uri = Uri::borrow_from(&state);
+            match host_from_request(&state, uri) {
+                Some(host) => redirect_response(&host, self.https_port, uri),
+                None => Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(Body::from("Missing Host header"))
+                    .expect("building a fixed 400 response should never fail"),
+            }
+        };
+
+        future::ok((state, response)).boxed()
+    }
+}
+
+/// Recovers just the hostname (no port) the client asked for, from the request's URI if it's
+/// absolute, otherwise from its `Host` header.
+fn host_from_request(state: &State, uri: &Uri) -> Option<String> {
+    if let Some(host) = uri.host() {
+        return Some(host.to_owned());
+    }
+
+    let host_header = HeaderMap::borrow_from(state).get(HOST)?.to_str().ok()?;
+    Some(strip_port(host_header).to_owned())
+}
+
+/// Strips a trailing `:port` from a `Host` header's authority, without mangling an IPv6 literal
+/// (e.g. `[::1]:8443`) whose own colons aren't a port separator — only a `:` after the closing
+/// `]` is.
+fn strip_port(authority: &str) -> &str {
+    if let Some(rest) = authority.strip_prefix('[') {
+        return match rest.find(']') {
+            Some(end) => &authority[..end + 2],
+            None => authority,
+        };
+    }
+
+    authority.split(':').next().unwrap_or(authority)
+}
+
+fn redirect_response(host: &str, https_port: u16, uri: &Uri) -> Response<Body> {
+    let path_and_query = uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+
+    let location = if https_port == 443 {
+        format!("https://{}{}", host, path_and_query)
+    } else {
+        format!("https://{}:{}{}", host, https_port, path_and_query)
+    };
+
+    Response::builder()
+        .status(StatusCode::MOVED_PERMANENTLY)
+        .header(LOCATION, location)
+        .body(Body::empty())
+        .expect("building a redirect response from a validated Location should never fail")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(response: &Response<Body>) -> &str {
+        response.headers().get(LOCATION).unwrap().to_str().unwrap()
+    }
+
+    #[test]
+    fn strip_port_handles_plain_host() {
+        assert_eq!(strip_port("example.com:8080"), "example.com");
+        assert_eq!(strip_port("example.com"), "example.com");
+    }
+
+    #[test]
+    fn strip_port_preserves_ipv6_literal() {
+        assert_eq!(strip_port("[::1]:8443"), "[::1]");
+        assert_eq!(strip_port("[::1]"), "[::1]");
+    }
+
+    #[test]
+    fn redirect_response_defaults_to_no_explicit_port_on_443() {
+        let uri: Uri = "/path?query=1".parse().unwrap();
+        let response = redirect_response("example.com", 443, &uri);
+
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(location(&response), "https://example.com/path?query=1");
+    }
+
+    #[test]
+    fn redirect_response_includes_non_default_port() {
+        let uri: Uri = "/path".parse().unwrap();
+        let response = redirect_response("example.com", 8443, &uri);
+
+        assert_eq!(location(&response), "https://example.com:8443/path");
+    }
+
+    #[test]
+    fn redirect_response_preserves_ipv6_literal_host() {
+        let uri: Uri = "/".parse().unwrap();
+        let response = redirect_response("[::1]", 8443, &uri);
+
+        assert_eq!(location(&response), "https://[::1]:8443/");
+    }
+}
@@ -0,0 +1,197 @@
+//! Hot-reloadable TLS configuration, so renewing a certificate (e.g. via ACME/Let's Encrypt)
+//! doesn't require restarting the listener.
+
+use arc_swap::ArcSwap;
+use futures_rustls::rustls;
+use log::{error, info};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::time::interval;
+
+/// A `rustls::ServerConfig` that can be swapped out while the server keeps running.
+///
+/// Connections accepted before a [`ReloadHandle::reload`] call keep using whichever config was
+/// active at the time; only connections accepted afterwards see the new one.
+#[derive(Clone)]
+pub struct ReloadableTlsConfig {
+    current: Arc<ArcSwap<rustls::ServerConfig>>,
+}
+
+/// A handle used to push a new `rustls::ServerConfig` into a running [`ReloadableTlsConfig`].
+#[derive(Clone)]
+pub struct ReloadHandle {
+    current: Arc<ArcSwap<rustls::ServerConfig>>,
+}
+
+impl ReloadableTlsConfig {
+    /// Wraps `initial` as the first active config, returning a handle that can push
+    /// replacements for it.
+    pub fn new(initial: rustls::ServerConfig) -> (ReloadableTlsConfig, ReloadHandle) {
+        let current = Arc::new(ArcSwap::from_pointee(initial));
+        (
+            ReloadableTlsConfig {
+                current: current.clone(),
+            },
+            ReloadHandle { current },
+        )
+    }
+
+    /// Returns the config that is currently active.
+    pub(crate) fn current(&self) -> Arc<rustls::ServerConfig> {
+        self.current.load_full()
+    }
+}
+
+impl ReloadHandle {
+    /// Atomically swaps in `new_config`. Connections already established are unaffected;
+    /// connections accepted from this point on use the new certificate chain.
+    pub fn reload(&self, new_config: rustls::ServerConfig) {
+        self.current.store(Arc::new(new_config));
+        info!(target: "gotham::tls", "TLS configuration reloaded");
+    }
+}
+
+/// Polls `cert_path`/`key_path` every `poll_interval` and calls `handle.reload` with the config
+/// produced by `build_config` whenever either file's modification time changes.
+///
+/// Polling (rather than OS file-watching) means this works the same way regardless of
+/// filesystem, including the atomic rename most ACME clients use to install a renewed
+/// certificate. Intended to be spawned as its own task alongside the server.
+pub async fn watch_files<F>(
+    handle: ReloadHandle,
+    cert_path: impl Into<PathBuf>,
+    key_path: impl Into<PathBuf>,
+    poll_interval: Duration,
+    mut build_config: F,
+) where
+    F: FnMut(&Path, &Path) -> std::io::Result<rustls::ServerConfig>,
+{
+    let cert_path = cert_path.into();
+    let key_path = key_path.into();
+    let mut last_modified = latest_mtime(&cert_path, &key_path);
+    let mut ticker = interval(poll_interval);
+
+    loop {
+        ticker.tick().await;
+
+        let modified = latest_mtime(&cert_path, &key_path);
+        if modified.is_none() || modified == last_modified {
+            continue;
+        }
+        last_modified = modified;
+
+        match build_config(&cert_path, &key_path) {
+            Ok(config) => handle.reload(config),
+            Err(e) => error!(
+                target: "gotham::tls",
+                "Failed to reload TLS configuration from {:?} / {:?}: {:?}",
+                cert_path, key_path, e
+            ),
+        }
+    }
+}
+
+fn latest_mtime(cert_path: &Path, key_path: &Path) -> Option<SystemTime> {
+    let cert = std::fs::metadata(cert_path).and_then(|m| m.modified()).ok();
+    let key = std::fs::metadata(key_path).and_then(|m| m.modified()).ok();
+    std::cmp::max(cert, key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::time::delay_for;
+
+    /// A file under `std::env::temp_dir()` that's removed again on drop, so tests don't need a
+    /// `tempfile`-style crate dependency just to exercise mtime polling.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "gotham-reload-test-{}-{}-{:?}",
+                name,
+                std::process::id(),
+                std::thread::current().id()
+            ));
+            std::fs::write(&path, b"initial").unwrap();
+            TempFile(path)
+        }
+
+        fn path(&self) -> &Path {
+            &self.0
+        }
+
+        /// Rewrites the file's contents, which bumps its mtime the same way a certificate
+        /// renewal would.
+        fn touch(&self) {
+            std::fs::write(&self.0, b"updated").unwrap();
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn latest_mtime_is_none_when_both_files_are_missing() {
+        let missing = Path::new("/no/such/path");
+        assert_eq!(latest_mtime(missing, missing), None);
+    }
+
+    #[test]
+    fn latest_mtime_is_the_newer_of_the_two_files() {
+        let cert = TempFile::new("cert");
+        let key = TempFile::new("key");
+
+        let cert_mtime = std::fs::metadata(cert.path()).unwrap().modified().unwrap();
+        let key_mtime = std::fs::metadata(key.path()).unwrap().modified().unwrap();
+
+        assert_eq!(
+            latest_mtime(cert.path(), key.path()),
+            Some(std::cmp::max(cert_mtime, key_mtime))
+        );
+    }
+
+    #[tokio::test]
+    async fn watch_files_reloads_once_per_genuine_mtime_change() {
+        let cert = TempFile::new("watch-cert");
+        let key = TempFile::new("watch-key");
+        let (_config, handle) = ReloadableTlsConfig::new(test_config());
+
+        let reloads = Arc::new(AtomicUsize::new(0));
+        let build_config_calls = reloads.clone();
+        tokio::spawn(watch_files(
+            handle,
+            cert.path().to_owned(),
+            key.path().to_owned(),
+            Duration::from_millis(10),
+            move |_, _| {
+                build_config_calls.fetch_add(1, Ordering::SeqCst);
+                Ok(test_config())
+            },
+        ));
+
+        // A few no-op ticks shouldn't trigger a reload: neither file's mtime has changed.
+        delay_for(Duration::from_millis(35)).await;
+        assert_eq!(reloads.load(Ordering::SeqCst), 0);
+
+        // Rewriting the cert file bumps its mtime, a genuine change `watch_files` should pick up
+        // on its next tick.
+        cert.touch();
+        delay_for(Duration::from_millis(35)).await;
+        assert_eq!(reloads.load(Ordering::SeqCst), 1);
+
+        // It shouldn't fire again on subsequent ticks until the mtime changes once more.
+        delay_for(Duration::from_millis(35)).await;
+        assert_eq!(reloads.load(Ordering::SeqCst), 1);
+    }
+
+    fn test_config() -> rustls::ServerConfig {
+        rustls::ServerConfig::new(rustls::NoClientAuth::new())
+    }
+}
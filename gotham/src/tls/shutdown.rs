@@ -0,0 +1,182 @@
+//! Graceful shutdown support for the TLS/HTTP server.
+
+use futures::channel::oneshot;
+use futures::future::{self, Either};
+use log::warn;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::delay_for;
+
+/// A handle to a running server, returned by `start_with_handle`/`init_server_with_handle`.
+///
+/// Call [`Handle::shutdown`] to stop the listener from accepting new connections and let the
+/// serving future resolve once every in-flight connection has completed.
+pub struct Handle {
+    trigger: oneshot::Sender<Option<Duration>>,
+    connections: Arc<AtomicUsize>,
+}
+
+/// The accept loop's half of a [`Handle`].
+pub(crate) struct ShutdownSignal {
+    trigger: oneshot::Receiver<Option<Duration>>,
+    connections: Arc<AtomicUsize>,
+}
+
+impl Handle {
+    /// Creates a linked `Handle`/`ShutdownSignal` pair for a new server.
+    pub(crate) fn new() -> (Handle, ShutdownSignal) {
+        let (trigger, rx) = oneshot::channel();
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        (
+            Handle {
+                trigger,
+                connections: connections.clone(),
+            },
+            ShutdownSignal {
+                trigger: rx,
+                connections,
+            },
+        )
+    }
+
+    /// Triggers a graceful shutdown of the server this handle was created for.
+    ///
+    /// The listener immediately stops accepting new connections. If `timeout` is `None`, the
+    /// serving future resolves once every in-flight connection has completed; if a timeout is
+    /// given, connections still outstanding once it elapses are dropped instead of waited on.
+    pub fn shutdown(self, timeout: Option<Duration>) {
+        // The receiving end is dropped once the server has finished shutting down, so a failed
+        // send just means there's nothing left to signal.
+        let _ = self.trigger.send(timeout);
+    }
+
+    /// Returns the number of connections currently being served.
+    pub fn connection_count(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+}
+
+impl ShutdownSignal {
+    /// Returns the shared counter of in-flight connections, to be incremented/decremented by a
+    /// [`ConnectionGuard`] around each accepted connection.
+    pub(crate) fn connections(&self) -> Arc<AtomicUsize> {
+        self.connections.clone()
+    }
+
+    /// Resolves with the caller's chosen drain timeout once [`Handle::shutdown`] is called.
+    ///
+    /// If the `Handle` is dropped instead of used, this must never resolve: a dropped handle
+    /// means "nobody will ever ask this server to shut down," not "shut down now," so on
+    /// `Canceled` it pends forever rather than falling back to a `None` timeout.
+    pub(crate) async fn triggered(&mut self) -> Option<Duration> {
+        match (&mut self.trigger).await {
+            Ok(timeout) => timeout,
+            Err(_canceled) => future::pending().await,
+        }
+    }
+}
+
+/// Waits for `connections` to reach zero, giving up early once `timeout` elapses.
+pub(crate) async fn drain(connections: Arc<AtomicUsize>, timeout: Option<Duration>) {
+    let wait_for_drain = async {
+        while connections.load(Ordering::SeqCst) > 0 {
+            delay_for(Duration::from_millis(10)).await;
+        }
+    };
+    futures::pin_mut!(wait_for_drain);
+
+    let timed_out = match timeout {
+        Some(timeout) => match future::select(wait_for_drain, delay_for(timeout)).await {
+            Either::Left(_) => false,
+            Either::Right(_) => true,
+        },
+        None => {
+            wait_for_drain.await;
+            false
+        }
+    };
+
+    if timed_out {
+        let remaining = connections.load(Ordering::SeqCst);
+        if remaining > 0 {
+            warn!(
+                target: "gotham::tls",
+                "shutdown timeout elapsed with {} connection(s) still in flight; dropping them",
+                remaining
+            );
+        }
+    }
+}
+
+/// RAII guard tracking a single in-flight connection; decrements the shared counter on drop.
+pub(crate) struct ConnectionGuard(Arc<AtomicUsize>);
+
+impl ConnectionGuard {
+    pub(crate) fn new(connections: Arc<AtomicUsize>) -> Self {
+        connections.fetch_add(1, Ordering::SeqCst);
+        ConnectionGuard(connections)
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn drain_returns_immediately_with_no_connections() {
+        let connections = Arc::new(AtomicUsize::new(0));
+        drain(connections, Some(Duration::from_secs(10))).await;
+    }
+
+    #[tokio::test]
+    async fn drain_waits_for_connections_to_finish() {
+        let connections = Arc::new(AtomicUsize::new(1));
+
+        let waiting = connections.clone();
+        tokio::spawn(async move {
+            delay_for(Duration::from_millis(20)).await;
+            waiting.fetch_sub(1, Ordering::SeqCst);
+        });
+
+        drain(connections.clone(), Some(Duration::from_secs(10))).await;
+        assert_eq!(connections.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn drain_gives_up_once_the_timeout_elapses() {
+        // Never decremented, so only the timeout can make this return.
+        let connections = Arc::new(AtomicUsize::new(1));
+        drain(connections.clone(), Some(Duration::from_millis(20))).await;
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn triggered_pends_forever_once_the_handle_is_dropped() {
+        let (handle, mut signal) = Handle::new();
+        drop(handle);
+
+        let timed_out = future::select(
+            Box::pin(signal.triggered()),
+            Box::pin(delay_for(Duration::from_millis(20))),
+        )
+        .await;
+        assert!(matches!(timed_out, Either::Right(_)));
+    }
+
+    #[tokio::test]
+    async fn triggered_resolves_once_shutdown_is_called() {
+        let (handle, mut signal) = Handle::new();
+        handle.shutdown(Some(Duration::from_secs(5)));
+
+        let timeout = signal.triggered().await;
+        assert_eq!(timeout, Some(Duration::from_secs(5)));
+    }
+}